@@ -0,0 +1,344 @@
+//! Parser for Steam's binary `appinfo.vdf` cache.
+//!
+//! This lets us resolve the installed files for an app id without having to
+//! sniff Steam's own command-line invocation, which changes shape across
+//! client versions.
+
+use anyhow::{Result, anyhow};
+use smol::fs;
+use std::collections::HashMap;
+use std::path::{Path, PathBuf};
+
+const MAGIC_V27: u32 = 0x07564427;
+const MAGIC_V28: u32 = 0x07564428;
+
+/// A node in the binary-VDF key/value tree.
+///
+/// Integer nodes (`0x02` int32 and `0x07` int64) are kept as `Int`, so
+/// integer-typed keys such as a depot's `dlcappid` stay visible to callers.
+#[derive(Debug, Clone)]
+pub enum Value {
+    Map(HashMap<Vec<u8>, Value>),
+    String(Vec<u8>),
+    Int(i64),
+}
+
+impl Value {
+    fn as_map(&self) -> Option<&HashMap<Vec<u8>, Value>> {
+        match self {
+            Value::Map(m) => Some(m),
+            _ => None,
+        }
+    }
+
+    fn as_str(&self) -> Option<&[u8]> {
+        match self {
+            Value::String(s) => Some(s),
+            _ => None,
+        }
+    }
+}
+
+/// A single `appinfo.vdf` entry's binary-VDF tree (the top-level
+/// `config`/`depots`/... keys). Callers already know the app id they queried
+/// `read_app_entry` with, so it isn't duplicated here.
+pub struct AppInfoEntry {
+    pub config: HashMap<Vec<u8>, Value>,
+}
+
+/// Reads and parses `<steam>/appcache/appinfo.vdf`, returning the entry for
+/// `app_id` if it is present.
+pub async fn read_app_entry(steam_path: &Path, app_id: u32) -> Result<Option<AppInfoEntry>> {
+    let appinfo_path = steam_path.join("appcache").join("appinfo.vdf");
+    if !appinfo_path.exists() {
+        return Ok(None);
+    }
+    let buf = fs::read(&appinfo_path).await?;
+    let mut pos = 0usize;
+    let magic = read_u32(&buf, &mut pos)?;
+    if magic != MAGIC_V27 && magic != MAGIC_V28 {
+        return Err(anyhow!("unrecognized appinfo.vdf magic: {magic:#x}"));
+    }
+    let _universe = read_u32(&buf, &mut pos)?;
+    loop {
+        let entry_app_id = read_u32(&buf, &mut pos)?;
+        if entry_app_id == 0 {
+            break;
+        }
+        let _size = read_u32(&buf, &mut pos)?;
+        let _info_state = read_u32(&buf, &mut pos)?;
+        let _last_updated = read_u32(&buf, &mut pos)?;
+        let _pics_token = read_u64(&buf, &mut pos)?;
+        let _text_vdf_sha1 = read_bytes(&buf, &mut pos, 20)?;
+        let _change_number = read_u32(&buf, &mut pos)?;
+        if magic == MAGIC_V28 {
+            let _binary_vdf_sha1 = read_bytes(&buf, &mut pos, 20)?;
+        }
+        let config = parse_value_tree(&buf, &mut pos)?;
+        if entry_app_id == app_id {
+            return Ok(Some(AppInfoEntry { config }));
+        }
+    }
+    Ok(None)
+}
+
+/// Walks `appinfo/config/installdir` for an entry, returning it as a `String`.
+pub fn installdir(entry: &AppInfoEntry) -> Option<String> {
+    let config = entry.config.get(b"config".as_slice())?.as_map()?;
+    let installdir = config.get(b"installdir".as_slice())?.as_str()?;
+    Some(String::from_utf8_lossy(installdir).into_owned())
+}
+
+/// Walks `appinfo/config/launch/*/executable` for an entry, returning every
+/// declared launch executable (relative to the install dir).
+pub fn launch_executables(entry: &AppInfoEntry) -> Vec<PathBuf> {
+    let mut executables = vec![];
+    let Some(config) = entry.config.get(b"config".as_slice()).and_then(Value::as_map) else {
+        return executables;
+    };
+    let Some(launch) = config.get(b"launch".as_slice()).and_then(Value::as_map) else {
+        return executables;
+    };
+    // `launch` entries are keyed by index ("0", "1", ...); walk them in
+    // numeric order so the primary entry is always picked first, not
+    // whatever order the underlying HashMap happens to iterate in (and not
+    // lexicographic order, which would put "10" before "2").
+    let mut keys: Vec<&Vec<u8>> = launch.keys().collect();
+    keys.sort_by_key(|key| {
+        std::str::from_utf8(key)
+            .ok()
+            .and_then(|k| k.parse::<u64>().ok())
+            .unwrap_or(u64::MAX)
+    });
+    for key in keys {
+        let Some(launch_entry) = launch.get(key).and_then(Value::as_map) else {
+            continue;
+        };
+        if let Some(executable) = launch_entry.get(b"executable".as_slice()).and_then(Value::as_str) {
+            executables.push(PathBuf::from(String::from_utf8_lossy(executable).into_owned()));
+        }
+    }
+    executables
+}
+
+/// Walks `appinfo/depots/*/config/oslist` for an entry, returning the set of
+/// operating systems declared across the app's own depots (depots that
+/// belong to a different app, i.e. carry a `dlcappid` key, are ignored).
+/// This lets callers tell a Windows-only title apart from a native one even
+/// when the launch executable's extension alone doesn't say so.
+pub fn depot_oslist(entry: &AppInfoEntry) -> Vec<String> {
+    let mut oslist = vec![];
+    let Some(depots) = entry.config.get(b"depots".as_slice()).and_then(Value::as_map) else {
+        return oslist;
+    };
+    for (key, depot) in depots {
+        // Skip non-depot keys ("branches", "baselanguages", ...): depot ids
+        // are always decimal.
+        if std::str::from_utf8(key).ok().and_then(|k| k.parse::<u32>().ok()).is_none() {
+            continue;
+        }
+        let Some(depot) = depot.as_map() else { continue };
+        if depot.contains_key(b"dlcappid".as_slice()) {
+            continue;
+        }
+        let Some(list) = depot
+            .get(b"config".as_slice())
+            .and_then(Value::as_map)
+            .and_then(|config| config.get(b"oslist".as_slice()))
+            .and_then(Value::as_str)
+        else {
+            continue;
+        };
+        for os in String::from_utf8_lossy(list).split(',') {
+            let os = os.trim();
+            if !os.is_empty() && !oslist.iter().any(|seen: &String| seen == os) {
+                oslist.push(os.to_owned());
+            }
+        }
+    }
+    oslist
+}
+
+fn parse_value_tree(buf: &[u8], pos: &mut usize) -> Result<HashMap<Vec<u8>, Value>> {
+    let mut map = HashMap::new();
+    loop {
+        let node_type = read_u8(buf, pos)?;
+        match node_type {
+            0x08 => break,
+            0x00 => {
+                let key = read_cstr(buf, pos)?;
+                let child = parse_value_tree(buf, pos)?;
+                map.insert(key, Value::Map(child));
+            }
+            0x01 => {
+                let key = read_cstr(buf, pos)?;
+                let value = read_cstr(buf, pos)?;
+                map.insert(key, Value::String(value));
+            }
+            0x02 => {
+                let key = read_cstr(buf, pos)?;
+                let value = read_i32(buf, pos)?;
+                map.insert(key, Value::Int(value.into()));
+            }
+            0x07 => {
+                let key = read_cstr(buf, pos)?;
+                let value = read_i64(buf, pos)?;
+                map.insert(key, Value::Int(value));
+            }
+            other => return Err(anyhow!("unknown binary vdf node type: {other:#x}")),
+        }
+    }
+    Ok(map)
+}
+
+fn read_bytes<'a>(buf: &'a [u8], pos: &mut usize, len: usize) -> Result<&'a [u8]> {
+    let end = *pos + len;
+    let slice = buf
+        .get(*pos..end)
+        .ok_or_else(|| anyhow!("unexpected end of appinfo.vdf"))?;
+    *pos = end;
+    Ok(slice)
+}
+
+fn read_u8(buf: &[u8], pos: &mut usize) -> Result<u8> {
+    Ok(read_bytes(buf, pos, 1)?[0])
+}
+
+fn read_u32(buf: &[u8], pos: &mut usize) -> Result<u32> {
+    let bytes: [u8; 4] = read_bytes(buf, pos, 4)?.try_into()?;
+    Ok(u32::from_le_bytes(bytes))
+}
+
+fn read_i32(buf: &[u8], pos: &mut usize) -> Result<i32> {
+    Ok(read_u32(buf, pos)? as i32)
+}
+
+fn read_u64(buf: &[u8], pos: &mut usize) -> Result<u64> {
+    let bytes: [u8; 8] = read_bytes(buf, pos, 8)?.try_into()?;
+    Ok(u64::from_le_bytes(bytes))
+}
+
+fn read_i64(buf: &[u8], pos: &mut usize) -> Result<i64> {
+    Ok(read_u64(buf, pos)? as i64)
+}
+
+fn read_cstr(buf: &[u8], pos: &mut usize) -> Result<Vec<u8>> {
+    let start = *pos;
+    let mut end = start;
+    while *buf.get(end).ok_or_else(|| anyhow!("unterminated string in appinfo.vdf"))? != 0 {
+        end += 1;
+    }
+    *pos = end + 1;
+    Ok(buf[start..end].to_vec())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn write_cstr(buf: &mut Vec<u8>, s: &[u8]) {
+        buf.extend_from_slice(s);
+        buf.push(0);
+    }
+
+    fn open_map(buf: &mut Vec<u8>, key: &[u8]) {
+        buf.push(0x00);
+        write_cstr(buf, key);
+    }
+
+    fn close_map(buf: &mut Vec<u8>) {
+        buf.push(0x08);
+    }
+
+    fn write_string(buf: &mut Vec<u8>, key: &[u8], value: &[u8]) {
+        buf.push(0x01);
+        write_cstr(buf, key);
+        write_cstr(buf, value);
+    }
+
+    fn write_int32(buf: &mut Vec<u8>, key: &[u8], value: i32) {
+        buf.push(0x02);
+        write_cstr(buf, key);
+        buf.extend_from_slice(&value.to_le_bytes());
+    }
+
+    /// Builds a one-entry `appinfo.vdf` (MAGIC_V27) for `app_id`: an
+    /// installdir, a numerically-ordered pair of launch entries, a
+    /// Windows-only depot, and a DLC depot that should be ignored.
+    fn sample_appinfo(app_id: u32) -> Vec<u8> {
+        let mut tree = vec![];
+        open_map(&mut tree, b"config");
+        write_string(&mut tree, b"installdir", b"GameDir");
+        open_map(&mut tree, b"launch");
+        open_map(&mut tree, b"10");
+        write_string(&mut tree, b"executable", b"extra.exe");
+        close_map(&mut tree);
+        open_map(&mut tree, b"0");
+        write_string(&mut tree, b"executable", b"game.exe");
+        close_map(&mut tree);
+        close_map(&mut tree); // launch
+        close_map(&mut tree); // config
+        open_map(&mut tree, b"depots");
+        open_map(&mut tree, b"100");
+        open_map(&mut tree, b"config");
+        write_string(&mut tree, b"oslist", b"windows");
+        close_map(&mut tree);
+        close_map(&mut tree); // depot 100
+        open_map(&mut tree, b"200");
+        write_int32(&mut tree, b"dlcappid", 456);
+        close_map(&mut tree); // depot 200
+        close_map(&mut tree); // depots
+        close_map(&mut tree); // root
+
+        let mut buf = vec![];
+        buf.extend_from_slice(&MAGIC_V27.to_le_bytes());
+        buf.extend_from_slice(&1u32.to_le_bytes()); // universe
+        buf.extend_from_slice(&app_id.to_le_bytes());
+        buf.extend_from_slice(&0u32.to_le_bytes()); // size
+        buf.extend_from_slice(&0u32.to_le_bytes()); // info_state
+        buf.extend_from_slice(&0u32.to_le_bytes()); // last_updated
+        buf.extend_from_slice(&0u64.to_le_bytes()); // pics_token
+        buf.extend_from_slice(&[0u8; 20]); // text_vdf_sha1
+        buf.extend_from_slice(&0u32.to_le_bytes()); // change_number
+        buf.extend_from_slice(&tree);
+        buf.extend_from_slice(&0u32.to_le_bytes()); // terminating app_id
+        buf
+    }
+
+    async fn write_appinfo(dir: &Path, app_id: u32) {
+        let appcache = dir.join("appcache");
+        fs::create_dir_all(&appcache).await.unwrap();
+        fs::write(appcache.join("appinfo.vdf"), sample_appinfo(app_id)).await.unwrap();
+    }
+
+    #[test]
+    fn reads_installdir_launch_order_and_depot_oslist() {
+        smol::block_on(async {
+            let dir = std::env::temp_dir().join("steam-pml-run-test-appinfo-found");
+            write_appinfo(&dir, 1001).await;
+
+            let entry = read_app_entry(&dir, 1001).await.unwrap().expect("entry present");
+            assert_eq!(installdir(&entry).as_deref(), Some("GameDir"));
+            // "0" must sort before "10", not after it as a lexicographic sort would.
+            assert_eq!(
+                launch_executables(&entry),
+                vec![PathBuf::from("game.exe"), PathBuf::from("extra.exe")]
+            );
+            assert_eq!(depot_oslist(&entry), vec!["windows".to_string()]);
+
+            fs::remove_dir_all(&dir).await.unwrap();
+        });
+    }
+
+    #[test]
+    fn read_app_entry_returns_none_for_unknown_app() {
+        smol::block_on(async {
+            let dir = std::env::temp_dir().join("steam-pml-run-test-appinfo-missing");
+            write_appinfo(&dir, 1001).await;
+
+            assert!(read_app_entry(&dir, 9999).await.unwrap().is_none());
+
+            fs::remove_dir_all(&dir).await.unwrap();
+        });
+    }
+}
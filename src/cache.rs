@@ -0,0 +1,285 @@
+//! Persistent warm-cache index.
+//!
+//! Shader caches and Proton prefixes rarely change between launches, so we
+//! remember a cheap fingerprint of each preloaded file under
+//! `$XDG_CACHE_HOME/steam-pml-run/<appid>.idx`. On the next run, a file whose
+//! fingerprint is unchanged and whose pages are still resident (per
+//! `mincore`) can skip re-locking entirely.
+
+use anyhow::{Result, anyhow};
+use nix::libc::{_SC_PAGESIZE, c_void, mincore, sysconf};
+use sha1::{Digest, Sha1};
+use smol::io::{AsyncReadExt, AsyncSeekExt, SeekFrom};
+use std::{
+    collections::HashMap,
+    env,
+    path::{Path, PathBuf},
+    time::{SystemTime, UNIX_EPOCH},
+};
+
+const SAMPLE_LEN: usize = 64 * 1024;
+const DIGEST_LEN: usize = 10;
+
+/// A cheap identity for a file: its size, mtime, and a truncated SHA1 of the
+/// first and last `SAMPLE_LEN` bytes.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Fingerprint {
+    pub size: u64,
+    pub mtime: i64,
+    pub digest: [u8; DIGEST_LEN],
+}
+
+/// An on-disk index of fingerprints, keyed by preloaded file path.
+pub struct Index {
+    path: PathBuf,
+    entries: HashMap<PathBuf, Fingerprint>,
+    dirty: bool,
+}
+
+impl Index {
+    /// Loads the index for `app_id`, or starts an empty one if it doesn't
+    /// exist yet or can't be parsed.
+    pub async fn load(app_id: &str) -> Result<Self> {
+        let path = index_path(app_id)?;
+        let entries = smol::fs::read_to_string(&path)
+            .await
+            .map(|content| parse_entries(&content))
+            .unwrap_or_default();
+        Ok(Self {
+            path,
+            entries,
+            dirty: false,
+        })
+    }
+
+    pub fn get(&self, path: &Path) -> Option<Fingerprint> {
+        self.entries.get(path).copied()
+    }
+
+    pub fn update(&mut self, path: PathBuf, fingerprint: Fingerprint) {
+        if self.entries.get(&path) != Some(&fingerprint) {
+            self.entries.insert(path, fingerprint);
+            self.dirty = true;
+        }
+    }
+
+    /// Writes the index back to disk if anything changed.
+    pub async fn flush(&self) -> Result<()> {
+        if !self.dirty {
+            return Ok(());
+        }
+        if let Some(parent) = self.path.parent() {
+            smol::fs::create_dir_all(parent).await?;
+        }
+        let mut out = String::new();
+        for (path, fp) in &self.entries {
+            out.push_str(&format!(
+                "{}\t{}\t{}\t{}\n",
+                fp.size,
+                fp.mtime,
+                hex(&fp.digest),
+                path.display()
+            ));
+        }
+        smol::fs::write(&self.path, out).await?;
+        Ok(())
+    }
+}
+
+fn index_path(app_id: &str) -> Result<PathBuf> {
+    let cache_home = env::var_os("XDG_CACHE_HOME")
+        .map(PathBuf::from)
+        .or_else(|| env::var_os("HOME").map(|home| PathBuf::from(home).join(".cache")))
+        .ok_or_else(|| anyhow!("could not determine a cache directory (XDG_CACHE_HOME/HOME unset)"))?;
+    Ok(cache_home.join("steam-pml-run").join(format!("{app_id}.idx")))
+}
+
+fn parse_entries(content: &str) -> HashMap<PathBuf, Fingerprint> {
+    let mut entries = HashMap::new();
+    for line in content.lines() {
+        let mut fields = line.splitn(4, '\t');
+        let (Some(size), Some(mtime), Some(digest_hex), Some(path)) =
+            (fields.next(), fields.next(), fields.next(), fields.next())
+        else {
+            continue;
+        };
+        let (Ok(size), Ok(mtime)) = (size.parse(), mtime.parse()) else {
+            continue;
+        };
+        let Some(digest) = parse_hex(digest_hex) else {
+            continue;
+        };
+        entries.insert(PathBuf::from(path), Fingerprint { size, mtime, digest });
+    }
+    entries
+}
+
+fn hex(bytes: &[u8]) -> String {
+    bytes.iter().map(|b| format!("{b:02x}")).collect()
+}
+
+fn parse_hex(s: &str) -> Option<[u8; DIGEST_LEN]> {
+    if s.len() != DIGEST_LEN * 2 {
+        return None;
+    }
+    let mut out = [0u8; DIGEST_LEN];
+    for (i, byte) in out.iter_mut().enumerate() {
+        *byte = u8::from_str_radix(&s[i * 2..i * 2 + 2], 16).ok()?;
+    }
+    Some(out)
+}
+
+pub fn mtime_secs(modified: SystemTime) -> i64 {
+    modified
+        .duration_since(UNIX_EPOCH)
+        .map(|d| d.as_secs() as i64)
+        .unwrap_or(0)
+}
+
+/// Fingerprints `file_path` from its already-known `size`/`mtime` plus the
+/// first and last `SAMPLE_LEN` bytes of its content.
+pub async fn sample_fingerprint(file_path: &Path, size: u64, mtime: i64) -> Result<Fingerprint> {
+    let mut file = smol::fs::File::open(file_path).await?;
+    let head_len = (size as usize).min(SAMPLE_LEN);
+    let mut head = vec![0u8; head_len];
+    read_fully(&mut file, &mut head).await?;
+
+    let mut hasher = Sha1::new();
+    hasher.update(&head);
+    if size as usize > SAMPLE_LEN {
+        let tail_len = SAMPLE_LEN.min(size as usize - head_len);
+        file.seek(SeekFrom::End(-(tail_len as i64))).await?;
+        let mut tail = vec![0u8; tail_len];
+        read_fully(&mut file, &mut tail).await?;
+        hasher.update(&tail);
+    }
+    let digest = hasher.finalize();
+    let mut truncated = [0u8; DIGEST_LEN];
+    truncated.copy_from_slice(&digest[..DIGEST_LEN]);
+    Ok(Fingerprint {
+        size,
+        mtime,
+        digest: truncated,
+    })
+}
+
+async fn read_fully(file: &mut smol::fs::File, buf: &mut [u8]) -> Result<()> {
+    let mut filled = 0;
+    while filled < buf.len() {
+        let n = file.read(&mut buf[filled..]).await?;
+        if n == 0 {
+            break;
+        }
+        filled += n;
+    }
+    Ok(())
+}
+
+/// Probes whether every page backing `mem` is currently resident, via
+/// `mincore`.
+pub fn is_resident(mem: *mut c_void, len: usize) -> bool {
+    if len == 0 {
+        return true;
+    }
+    let page_size = unsafe { sysconf(_SC_PAGESIZE) } as usize;
+    if page_size == 0 {
+        return false;
+    }
+    let page_count = len.div_ceil(page_size);
+    let mut residency = vec![0u8; page_count];
+    let rc = unsafe { mincore(mem, len, residency.as_mut_ptr()) };
+    rc == 0 && residency.iter().all(|b| b & 1 == 1)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use nix::libc::{MAP_ANONYMOUS, MAP_FAILED, MAP_PRIVATE, PROT_READ, PROT_WRITE, mmap64, munmap};
+    use std::ptr::null_mut;
+
+    #[test]
+    fn hex_and_parse_hex_round_trip() {
+        let digest: [u8; DIGEST_LEN] = [0x01, 0xab, 0x00, 0xff, 0x10, 0x20, 0x30, 0x40, 0x50, 0xee];
+        assert_eq!(parse_hex(&hex(&digest)), Some(digest));
+    }
+
+    #[test]
+    fn parse_hex_rejects_the_wrong_length() {
+        assert_eq!(parse_hex("abcd"), None);
+    }
+
+    #[test]
+    fn parse_entries_round_trips_through_the_flush_format() {
+        let fp = Fingerprint {
+            size: 1234,
+            mtime: -5,
+            digest: [9; DIGEST_LEN],
+        };
+        let line = format!("{}\t{}\t{}\t{}\n", fp.size, fp.mtime, hex(&fp.digest), "/some/file");
+        let entries = parse_entries(&line);
+        assert_eq!(entries.get(Path::new("/some/file")), Some(&fp));
+    }
+
+    #[test]
+    fn parse_entries_skips_malformed_lines() {
+        let entries = parse_entries("not\tenough\tfields\nalso garbage\n");
+        assert!(entries.is_empty());
+    }
+
+    #[test]
+    fn sample_fingerprint_hashes_a_file_smaller_than_the_sample_window() {
+        smol::block_on(async {
+            let path = std::env::temp_dir().join("steam-pml-run-test-cache-small");
+            smol::fs::write(&path, b"hello world").await.unwrap();
+
+            let fp = sample_fingerprint(&path, 11, 42).await.unwrap();
+            assert_eq!(fp.size, 11);
+            assert_eq!(fp.mtime, 42);
+            assert_eq!(fp, sample_fingerprint(&path, 11, 42).await.unwrap());
+
+            smol::fs::remove_file(&path).await.unwrap();
+        });
+    }
+
+    #[test]
+    fn sample_fingerprint_only_hashes_head_and_tail_of_a_larger_file() {
+        smol::block_on(async {
+            let path = std::env::temp_dir().join("steam-pml-run-test-cache-large");
+            let size = SAMPLE_LEN * 2 + 17;
+            let content = vec![0u8; size];
+            smol::fs::write(&path, &content).await.unwrap();
+            let fp = sample_fingerprint(&path, size as u64, 7).await.unwrap();
+
+            // A byte changed outside both sampled windows must not move the digest.
+            let mut untouched_middle = content.clone();
+            untouched_middle[size / 2] ^= 0xff;
+            smol::fs::write(&path, &untouched_middle).await.unwrap();
+            let fp_after_middle_change = sample_fingerprint(&path, size as u64, 7).await.unwrap();
+            assert_eq!(fp.digest, fp_after_middle_change.digest);
+
+            // A byte changed in the tail window must move the digest.
+            let mut touched_tail = content.clone();
+            *touched_tail.last_mut().unwrap() ^= 0xff;
+            smol::fs::write(&path, &touched_tail).await.unwrap();
+            let fp_after_tail_change = sample_fingerprint(&path, size as u64, 7).await.unwrap();
+            assert_ne!(fp.digest, fp_after_tail_change.digest);
+
+            smol::fs::remove_file(&path).await.unwrap();
+        });
+    }
+
+    #[test]
+    fn is_resident_reports_touched_anonymous_pages_as_resident() {
+        let page_size = unsafe { sysconf(_SC_PAGESIZE) } as usize;
+        let len = page_size * 2;
+        unsafe {
+            let mem = mmap64(null_mut(), len, PROT_READ | PROT_WRITE, MAP_PRIVATE | MAP_ANONYMOUS, -1, 0);
+            assert_ne!(mem, MAP_FAILED);
+            std::ptr::write_bytes(mem as *mut u8, 1, len);
+
+            assert!(is_resident(mem, len));
+
+            munmap(mem, len);
+        }
+    }
+}
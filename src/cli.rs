@@ -0,0 +1,55 @@
+//! Command-line surface for `steam-pml-run`.
+
+use argh::FromArgs;
+use std::str::FromStr;
+
+/// Preload and mlock a Steam game's files before launching it.
+#[derive(FromArgs)]
+pub struct Cli {
+    /// override the free-memory reserve (`MIN_KEEP_MEM_SIZE`) that preloading keeps clear, in bytes
+    #[argh(option)]
+    pub reserve_bytes: Option<u64>,
+
+    /// resolve and print the files that would be preloaded, along with their total size, without mmapping or launching anything
+    #[argh(switch)]
+    pub dry_run: bool,
+
+    /// restrict preloading to one category (`shader`, `game`, `proton`); may be passed multiple times, defaults to all categories
+    #[argh(option)]
+    pub only: Vec<PreloadCategory>,
+
+    /// the wrapped command and its arguments, e.g. `-- %command%`
+    #[argh(positional)]
+    pub command: Vec<String>,
+}
+
+impl Cli {
+    pub fn from_env() -> Self {
+        argh::from_env()
+    }
+
+    /// Whether `category` should be preloaded given the `--only` selectors.
+    pub fn wants(&self, category: PreloadCategory) -> bool {
+        self.only.is_empty() || self.only.contains(&category)
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum PreloadCategory {
+    Shader,
+    Game,
+    Proton,
+}
+
+impl FromStr for PreloadCategory {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "shader" => Ok(PreloadCategory::Shader),
+            "game" => Ok(PreloadCategory::Game),
+            "proton" => Ok(PreloadCategory::Proton),
+            other => Err(format!("unknown preload category: {other} (expected shader, game, or proton)")),
+        }
+    }
+}
@@ -1,5 +1,14 @@
+mod appinfo;
+mod cache;
+mod cli;
+mod vdf;
+
 use anyhow::{Result, anyhow};
-use nix::libc::{MAP_FAILED, MAP_SHARED, PROT_READ, mlock, mmap64, munmap};
+use nix::errno::Errno;
+use nix::libc::{
+    MAP_FAILED, MAP_SHARED, POSIX_MADV_WILLNEED, PROT_READ, RLIMIT_MEMLOCK, mlock, mmap64, munlock,
+    munmap, posix_madvise, rlimit,
+};
 use smol::{
     block_on,
     fs::{self, read_dir},
@@ -8,9 +17,11 @@ use smol::{
     stream::StreamExt,
 };
 use std::{
+    collections::HashSet,
     env,
     ffi::{OsStr, c_void},
-    ops::{AddAssign, DerefMut},
+    mem::MaybeUninit,
+    ops::{AddAssign, Deref, DerefMut, SubAssign},
     os::fd::{AsFd, AsRawFd},
     path::{Path, PathBuf},
     process::{self, ExitStatus, Stdio},
@@ -18,7 +29,9 @@ use std::{
     sync::Arc,
 };
 use sysinfo::System;
-const STEAM_APPS: &str = "steamapps";
+
+use cli::{Cli, PreloadCategory};
+pub(crate) const STEAM_APPS: &str = "steamapps";
 const COMMON: &str = "common";
 const COMPATDATA: &str = "compatdata";
 const SHADERCACHE: &str = "shadercache";
@@ -26,25 +39,28 @@ const SHADERCACHE: &str = "shadercache";
 const MIN_KEEP_MEM_SIZE: u64 = 4 * 1024 * 1024 * 1024;
 
 fn main() -> Result<()> {
-    let args: Vec<String> = env::args().collect();
-    if args.len() > 2 {
-        let exit_status = block_on(run(args))?;
-        if let Some(code) = exit_status.code() {
-            process::exit(code);
-        } else {
-            Err(anyhow!("Unknown exit status."))
-        }
+    let cli = Cli::from_env();
+    if cli.command.is_empty() {
+        return Err(anyhow!("Args is not enough."));
+    }
+    if cli.dry_run {
+        return block_on(dry_run(&cli));
+    }
+    let exit_status = block_on(run(cli))?;
+    if let Some(code) = exit_status.code() {
+        process::exit(code);
     } else {
-        Err(anyhow!("Args is not enough."))
+        Err(anyhow!("Unknown exit status."))
     }
 }
 
-async fn run(args: Vec<String>) -> Result<ExitStatus> {
-    let command = args[1].to_owned();
-    let args = args[2..].to_owned();
+async fn run(cli: Cli) -> Result<ExitStatus> {
+    let reserve_bytes = cli.reserve_bytes.unwrap_or(MIN_KEEP_MEM_SIZE);
+    let command = cli.command[0].to_owned();
+    let args = cli.command[1..].to_owned();
     let (rc_result, pl_result) = smol::future::zip(
         run_command(command.clone(), args.clone()),
-        pre_load_files(args),
+        pre_load_files(args, &cli, reserve_bytes),
     )
     .await;
     let status = rc_result?;
@@ -57,6 +73,55 @@ fn drops(mems: Vec<MappedMem>) {
     }
 }
 
+/// Resolves the files that would be preloaded and prints them along with
+/// how many of them, and how many bytes, would actually end up locked once
+/// the same free-memory and `RLIMIT_MEMLOCK` budget `load_file` enforces is
+/// applied — without mmapping or launching the wrapped command.
+async fn dry_run(cli: &Cli) -> Result<()> {
+    let args = cli.command[1..].to_owned();
+    let targets = resolve_preload_targets(&args, cli).await?;
+    let resolved = resolve_file_paths(targets.into_iter().map(|t| t.path).collect()).await?;
+    let total_bytes: u64 = resolved.iter().map(|(_, size)| size).sum();
+
+    let mut sys = System::new_all();
+    sys.refresh_all();
+    let reserve_bytes = cli.reserve_bytes.unwrap_or(MIN_KEEP_MEM_SIZE);
+    let memlock_limit = query_memlock_limit()?;
+    let (lockable_count, lockable_bytes) = simulate_lock_budget(&resolved, &sys, reserve_bytes, memlock_limit);
+
+    println!("resolved {} files ({total_bytes} bytes total):", resolved.len());
+    for (path, size) in &resolved {
+        println!("{size}\t{}", path.display());
+    }
+    println!("would lock {lockable_count} of them ({lockable_bytes} bytes); the rest would fall back to readahead or be skipped");
+    Ok(())
+}
+
+/// Replays the same eligibility gate `load_file` uses (free-memory headroom,
+/// then cumulative size against `RLIMIT_MEMLOCK`) to report how much of the
+/// resolved set would actually be locked, as opposed to merely mapped.
+fn simulate_lock_budget(
+    resolved: &[(PathBuf, u64)],
+    sys: &System,
+    reserve_bytes: u64,
+    memlock_limit: u64,
+) -> (usize, u64) {
+    let mut locked_bytes = 0u64;
+    let mut locked_count = 0usize;
+    for (_, size) in resolved {
+        let free_mem = sys.free_memory().saturating_sub(*size);
+        if free_mem <= reserve_bytes {
+            continue;
+        }
+        if locked_bytes.saturating_add(*size) > memlock_limit {
+            continue;
+        }
+        locked_bytes += size;
+        locked_count += 1;
+    }
+    (locked_count, locked_bytes)
+}
+
 async fn run_command(command: String, args: Vec<String>) -> Result<ExitStatus> {
     let status = Command::new(command)
         .args(args)
@@ -67,13 +132,24 @@ async fn run_command(command: String, args: Vec<String>) -> Result<ExitStatus> {
     Ok(status)
 }
 
-async fn pre_load_files(args: Vec<String>) -> Result<Vec<MappedMem>> {
-    let steam_game =
-        ditect_steam_game(&args).ok_or(anyhow!("Can not find steamapss directory."))?;
-    let app_id = ditect_app_id(&args).ok_or(anyhow!("Can not find steam app id."))?;
-    let in_proton = if let Some(ext) = steam_game.exefile_path.extension().and_then(OsStr::to_str)
-        && ext == "exe"
-    {
+/// A file or directory to warm, tagged with which `--only` selector governs it.
+struct PreloadTarget {
+    category: PreloadCategory,
+    path: PathBuf,
+}
+
+async fn resolve_preload_targets(args: &[String], cli: &Cli) -> Result<Vec<PreloadTarget>> {
+    let app_id = ditect_app_id(args).ok_or(anyhow!("Can not find steam app id."))?;
+    let steam_game = ditect_steam_game(args, &app_id)
+        .await
+        .ok_or(anyhow!("Can not find steamapss directory."))?;
+    // A game runs under Proton when its launch executable is a Windows
+    // binary, or (when that extension check is inconclusive, e.g. a wrapper
+    // script) when its own depots only declare Windows in their oslist.
+    let is_windows_exe = steam_game.exefile_path.extension().and_then(OsStr::to_str) == Some("exe");
+    let is_windows_only_depot = steam_game.depot_oslist.iter().any(|os| os == "windows")
+        && !steam_game.depot_oslist.iter().any(|os| os == "linux");
+    let in_proton = if is_windows_exe || is_windows_only_depot {
         Some((
             steam_game.common_dir.join("Steam.dll"),
             steam_game.steamapps_dir.join(COMPATDATA).join(&app_id),
@@ -81,20 +157,67 @@ async fn pre_load_files(args: Vec<String>) -> Result<Vec<MappedMem>> {
     } else {
         None
     };
-    let mut load_files_and_dirs = vec![
-        steam_game.game_dir,
-        steam_game.steamapps_dir.join(SHADERCACHE).join(&app_id),
+    let mut targets = vec![
+        PreloadTarget {
+            category: PreloadCategory::Game,
+            path: steam_game.game_dir,
+        },
+        PreloadTarget {
+            category: PreloadCategory::Shader,
+            path: steam_game.steamapps_dir.join(SHADERCACHE).join(&app_id),
+        },
     ];
     if let Some((steam_dll_path, proton_env_dir)) = in_proton {
-        load_files_and_dirs.push(steam_dll_path);
-        load_files_and_dirs.push(proton_env_dir);
+        targets.push(PreloadTarget {
+            category: PreloadCategory::Proton,
+            path: steam_dll_path,
+        });
+        targets.push(PreloadTarget {
+            category: PreloadCategory::Proton,
+            path: proton_env_dir,
+        });
     }
+    Ok(targets.into_iter().filter(|t| cli.wants(t.category)).collect())
+}
+
+async fn pre_load_files(args: Vec<String>, cli: &Cli, reserve_bytes: u64) -> Result<Vec<MappedMem>> {
+    let app_id = ditect_app_id(&args).ok_or(anyhow!("Can not find steam app id."))?;
+    let targets = resolve_preload_targets(&args, cli).await?;
+    let load_files_and_dirs: Vec<PathBuf> = targets.into_iter().map(|t| t.path).collect();
     println!("pre load files:");
     println!("{load_files_and_dirs:?}");
     let mut sys = System::new_all();
     sys.refresh_all();
     let cached_mem_size = Arc::new(Mutex::new(0));
-    load_file_paths(load_files_and_dirs, &sys, cached_mem_size).await
+    let memlock_limit = query_memlock_limit()?;
+    let index = Arc::new(Mutex::new(cache::Index::load(&app_id).await?));
+    let mms = load_file_paths(
+        load_files_and_dirs,
+        &sys,
+        cached_mem_size,
+        reserve_bytes,
+        memlock_limit,
+        &index,
+    )
+    .await?;
+    index.lock().await.flush().await?;
+    Ok(mms)
+}
+
+/// Reads the process's `RLIMIT_MEMLOCK` so the cumulative lock size can be
+/// capped against it, not just against free memory.
+fn query_memlock_limit() -> Result<u64> {
+    let mut limit = MaybeUninit::<rlimit>::uninit();
+    let rc = unsafe { nix::libc::getrlimit(RLIMIT_MEMLOCK, limit.as_mut_ptr()) };
+    if rc != 0 {
+        return Err(anyhow!("getrlimit(RLIMIT_MEMLOCK) failed: {}", Errno::last()));
+    }
+    let limit = unsafe { limit.assume_init() };
+    Ok(if limit.rlim_cur == nix::libc::RLIM_INFINITY {
+        u64::MAX
+    } else {
+        limit.rlim_cur
+    })
 }
 
 struct SteamGame {
@@ -102,9 +225,87 @@ struct SteamGame {
     steamapps_dir: PathBuf,
     game_dir: PathBuf,
     common_dir: PathBuf,
+    /// Operating systems declared across the app's own depots
+    /// (`appinfo/depots/*/config/oslist`); empty when resolved via the argv
+    /// heuristic, which has no access to appinfo.vdf.
+    depot_oslist: Vec<String>,
 }
 
-fn ditect_steam_game(args: &[String]) -> Option<SteamGame> {
+/// Resolves the installed game for `app_id`, preferring Steam's own
+/// `appinfo.vdf` record and falling back to scraping the wrapped command's
+/// argv when the cache is missing or unparsable.
+async fn ditect_steam_game(args: &[String], app_id: &str) -> Option<SteamGame> {
+    let heuristic = ditect_steam_game_from_args(args);
+    for steam_path in candidate_steam_paths(&heuristic) {
+        match ditect_steam_game_from_appinfo(&steam_path, &heuristic, app_id).await {
+            Ok(Some(game)) => return Some(game),
+            Ok(None) => {}
+            Err(err) => eprintln!(
+                "failed to resolve steam game from appinfo.vdf at {}: {err:#}",
+                steam_path.display()
+            ),
+        }
+    }
+    heuristic
+}
+
+/// Candidate Steam installation roots to try `appinfo.vdf` resolution
+/// against, so that resolution does not rest on the wrapped command's argv
+/// alone: an explicit environment hint, the argv heuristic's own guess (if
+/// it found one), and finally the well-known default install locations.
+/// Only existing directories are kept, each once.
+fn candidate_steam_paths(heuristic: &Option<SteamGame>) -> Vec<PathBuf> {
+    let mut candidates = vec![];
+    if let Ok(path) = env::var("STEAM_COMPAT_CLIENT_INSTALL_PATH") {
+        candidates.push(PathBuf::from(path));
+    }
+    if let Some(steam_path) = heuristic.as_ref().and_then(|g| g.steamapps_dir.parent()) {
+        candidates.push(steam_path.to_path_buf());
+    }
+    if let Some(home) = env::var_os("HOME") {
+        candidates.push(PathBuf::from(&home).join(".steam").join("steam"));
+        candidates.push(PathBuf::from(&home).join(".local").join("share").join("Steam"));
+    }
+    let mut seen = HashSet::new();
+    candidates.retain(|path| path.is_dir() && seen.insert(path.clone()));
+    candidates
+}
+
+async fn ditect_steam_game_from_appinfo(
+    steam_path: &Path,
+    heuristic: &Option<SteamGame>,
+    app_id: &str,
+) -> Result<Option<SteamGame>> {
+    let app_id_num: u32 = app_id.parse()?;
+    let Some(entry) = appinfo::read_app_entry(steam_path, app_id_num).await? else {
+        return Ok(None);
+    };
+    let Some(installdir) = appinfo::installdir(&entry) else {
+        return Ok(None);
+    };
+    let libraries = vdf::discover_libraries(steam_path).await?;
+    let library_root = vdf::find_library_for_app(&libraries, app_id)
+        .map(|library| library.path.clone())
+        .unwrap_or_else(|| steam_path.to_path_buf());
+    let steamapps_dir = library_root.join(STEAM_APPS);
+    let common_dir = steamapps_dir.join(COMMON);
+    let game_dir = common_dir.join(&installdir);
+    let exefile_path = appinfo::launch_executables(&entry)
+        .into_iter()
+        .map(|exe| game_dir.join(exe))
+        .next()
+        .or_else(|| heuristic.as_ref().map(|g| g.exefile_path.clone()))
+        .ok_or_else(|| anyhow!("appinfo.vdf entry has no launch executable for app {app_id}"))?;
+    Ok(Some(SteamGame {
+        exefile_path,
+        steamapps_dir,
+        game_dir,
+        common_dir,
+        depot_oslist: appinfo::depot_oslist(&entry),
+    }))
+}
+
+fn ditect_steam_game_from_args(args: &[String]) -> Option<SteamGame> {
     for arg in args {
         if arg.contains(STEAM_APPS) {
             let target_path = Path::new(arg);
@@ -118,6 +319,7 @@ fn ditect_steam_game(args: &[String]) -> Option<SteamGame> {
                         steamapps_dir: steam_target.to_path_buf(),
                         game_dir: t.to_path_buf(),
                         common_dir: common_target.to_path_buf(),
+                        depot_oslist: vec![],
                     });
                 }
             }
@@ -139,13 +341,21 @@ fn ditect_app_id(args: &[String]) -> Option<String> {
 struct MappedMem {
     addr: *mut c_void,
     len: usize,
+    /// Whether `addr` is actually `mlock`ed, as opposed to merely mapped and
+    /// `madvise`d. Only genuinely locked regions need `munlock`ing.
+    locked: bool,
 }
 impl MappedMem {
-    fn new(addr: *mut c_void, len: usize) -> Self {
-        Self { addr, len }
+    fn new(addr: *mut c_void, len: usize, locked: bool) -> Self {
+        Self { addr, len, locked }
     }
     fn release(&mut self) {
-        unsafe { munmap(self.addr, self.len) };
+        unsafe {
+            if self.locked {
+                munlock(self.addr, self.len);
+            }
+            munmap(self.addr, self.len);
+        }
     }
 }
 
@@ -153,6 +363,9 @@ async fn load_file_paths(
     file_and_dirs: Vec<PathBuf>,
     sys: &System,
     cached_mem_size: Arc<Mutex<u64>>,
+    reserve_bytes: u64,
+    memlock_limit: u64,
+    index: &Arc<Mutex<cache::Index>>,
 ) -> Result<Vec<MappedMem>> {
     let mut tasks = vec![];
     for file_or_dir in file_and_dirs {
@@ -160,6 +373,9 @@ async fn load_file_paths(
             file_or_dir,
             sys,
             cached_mem_size.clone(),
+            reserve_bytes,
+            memlock_limit,
+            index,
         )));
     }
     let mut mms = vec![];
@@ -173,17 +389,20 @@ async fn load_path(
     path: impl AsRef<Path>,
     sys: &System,
     cached_mem_size: Arc<Mutex<u64>>,
+    reserve_bytes: u64,
+    memlock_limit: u64,
+    index: &Arc<Mutex<cache::Index>>,
 ) -> Result<Vec<MappedMem>> {
     let path = path.as_ref();
     if path.exists() {
         if path.is_file() {
-            if let Some(mmap) = load_file(path, sys, cached_mem_size).await? {
+            if let Some(mmap) = load_file(path, sys, cached_mem_size, reserve_bytes, memlock_limit, index).await? {
                 Ok(vec![mmap])
             } else {
                 Ok(vec![])
             }
         } else if path.is_dir() {
-            Ok(load_dir(path, sys, cached_mem_size).await?)
+            Ok(load_dir(path, sys, cached_mem_size, reserve_bytes, memlock_limit, index).await?)
         } else {
             Err(anyhow!("unknown path."))
         }
@@ -197,52 +416,124 @@ async fn load_file(
     file_path: impl AsRef<Path>,
     sys: &System,
     cached_mem_size: Arc<Mutex<u64>>,
+    reserve_bytes: u64,
+    memlock_limit: u64,
+    index: &Arc<Mutex<cache::Index>>,
 ) -> Result<Option<MappedMem>> {
-    let file_size = fs::metadata(&file_path).await?.len() as usize;
-    let need_mlock = {
-        let mut cms = cached_mem_size.lock().await;
-        let lock_size = file_size as u64;
-        let free_mem = sys.free_memory() - lock_size;
-        if free_mem > MIN_KEEP_MEM_SIZE {
-            cms.deref_mut().add_assign(lock_size);
-            true
-        } else {
-            false
+    let file_path = file_path.as_ref();
+    let metadata = fs::metadata(&file_path).await?;
+    let file_size = metadata.len() as usize;
+    let lock_size = file_size as u64;
+
+    let free_mem = sys.free_memory().saturating_sub(lock_size);
+    if free_mem <= reserve_bytes {
+        // Not enough headroom to justify even reading this file in.
+        return Ok(None);
+    }
+
+    let mtime = cache::mtime_secs(metadata.modified()?);
+    let previous = index.lock().await.get(file_path);
+    let fingerprint = cache::sample_fingerprint(file_path, lock_size, mtime).await?;
+    let unchanged = previous == Some(fingerprint);
+
+    let file = fs::File::open(&file_path).await?;
+    let fd = file.as_fd();
+    unsafe {
+        let mem = mmap64(
+            null_mut(),
+            file_size,
+            PROT_READ,
+            MAP_SHARED,
+            fd.as_raw_fd(),
+            0,
+        );
+        if mem == MAP_FAILED {
+            return Ok(None);
         }
-    };
-    if need_mlock {
-        let file = fs::File::open(&file_path).await?;
-        let fd = file.as_fd();
+        if unchanged && cache::is_resident(mem, file_size) {
+            // Already warm from a previous run; nothing to do.
+            munmap(mem, file_size);
+            return Ok(None);
+        }
+        index.lock().await.update(file_path.to_path_buf(), fingerprint);
 
-        unsafe {
-            let mem = mmap64(
-                null_mut(),
-                file_size,
-                PROT_READ,
-                MAP_SHARED,
-                fd.as_raw_fd(),
-                0,
-            );
-            if mem != MAP_FAILED {
-                mlock(mem, file_size);
-                Ok(Some(MappedMem::new(mem, file_size)))
+        let attempt_mlock = {
+            let mut cms = cached_mem_size.lock().await;
+            if cms.deref().saturating_add(lock_size) <= memlock_limit {
+                cms.deref_mut().add_assign(lock_size);
+                true
             } else {
-                Ok(None)
+                false
             }
-        }
-    } else {
-        Ok(None)
+        };
+        let locked = if attempt_mlock && mlock(mem, file_size) == 0 {
+            true
+        } else {
+            if attempt_mlock {
+                // We reserved this size against the rlimit budget but the
+                // kernel rejected the lock; give it back.
+                let errno = Errno::last();
+                if !matches!(errno, Errno::EAGAIN | Errno::ENOMEM) {
+                    eprintln!("mlock failed unexpectedly, falling back to readahead: {errno}");
+                }
+                let mut cms = cached_mem_size.lock().await;
+                cms.deref_mut().sub_assign(lock_size);
+            }
+            posix_madvise(mem, file_size, POSIX_MADV_WILLNEED);
+            false
+        };
+        Ok(Some(MappedMem::new(mem, file_size, locked)))
     }
 }
 async fn load_dir(
     dir_path: impl AsRef<Path>,
     sys: &System,
     cached_mem_size: Arc<Mutex<u64>>,
+    reserve_bytes: u64,
+    memlock_limit: u64,
+    index: &Arc<Mutex<cache::Index>>,
 ) -> Result<Vec<MappedMem>> {
     let mut paths = vec![];
     let mut entries = read_dir(dir_path).await?;
     while let Some(entry) = entries.try_next().await? {
         paths.push(entry.path());
     }
-    load_file_paths(paths, sys, cached_mem_size).await
+    load_file_paths(paths, sys, cached_mem_size, reserve_bytes, memlock_limit, index).await
+}
+
+async fn resolve_file_paths(file_and_dirs: Vec<PathBuf>) -> Result<Vec<(PathBuf, u64)>> {
+    let mut tasks = vec![];
+    for file_or_dir in file_and_dirs {
+        tasks.push(Box::pin(resolve_path(file_or_dir)));
+    }
+    let mut resolved = vec![];
+    for task in tasks {
+        resolved.append(&mut task.await?);
+    }
+    Ok(resolved)
+}
+
+async fn resolve_path(path: impl AsRef<Path>) -> Result<Vec<(PathBuf, u64)>> {
+    let path = path.as_ref();
+    if path.exists() {
+        if path.is_file() {
+            let size = fs::metadata(path).await?.len();
+            Ok(vec![(path.to_path_buf(), size)])
+        } else if path.is_dir() {
+            Ok(resolve_dir(path).await?)
+        } else {
+            Err(anyhow!("unknown path."))
+        }
+    } else {
+        Ok(vec![])
+    }
+}
+
+async fn resolve_dir(dir_path: impl AsRef<Path>) -> Result<Vec<(PathBuf, u64)>> {
+    let mut paths = vec![];
+    let mut entries = read_dir(dir_path).await?;
+    while let Some(entry) = entries.try_next().await? {
+        paths.push(entry.path());
+    }
+    resolve_file_paths(paths).await
 }
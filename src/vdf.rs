@@ -0,0 +1,212 @@
+//! Recursive-descent parser for Steam's text VDF format, used to read
+//! `libraryfolders.vdf` so every Steam library (not just the one that
+//! happened to appear in argv) can be searched for an app id.
+
+use anyhow::{Result, anyhow};
+use smol::fs;
+use std::collections::HashMap;
+use std::iter::Peekable;
+use std::path::{Path, PathBuf};
+use std::str::Chars;
+
+use crate::STEAM_APPS;
+
+/// A node in a text-VDF document: either a leaf string or a nested map.
+#[derive(Debug, Clone)]
+pub enum Node {
+    String(String),
+    Map(Dictionary),
+}
+
+pub type Dictionary = HashMap<String, Node>;
+
+/// One entry from `libraryfolders.vdf`: the library's root path and the
+/// app ids (with reported install size) it contains.
+pub struct Library {
+    pub path: PathBuf,
+    pub apps: HashMap<String, u64>,
+}
+
+/// Parses every library folder declared in
+/// `<steam_path>/steamapps/libraryfolders.vdf`.
+pub async fn discover_libraries(steam_path: &Path) -> Result<Vec<Library>> {
+    let path = steam_path.join(STEAM_APPS).join("libraryfolders.vdf");
+    if !path.exists() {
+        return Ok(vec![]);
+    }
+    let content = fs::read_to_string(&path).await?;
+    let dict = parse(&content)?;
+    // The whole file is wrapped in a single top-level "libraryfolders" key;
+    // the actual per-library entries ("0", "1", ...) are its children.
+    let Some(Node::Map(libraries_node)) = dict
+        .iter()
+        .find(|(key, _)| key.eq_ignore_ascii_case("libraryfolders"))
+        .map(|(_, node)| node)
+    else {
+        return Ok(vec![]);
+    };
+    let mut libraries = vec![];
+    for node in libraries_node.values() {
+        let Node::Map(entry) = node else { continue };
+        let Some(Node::String(path)) = entry.get("path") else {
+            continue;
+        };
+        let mut apps = HashMap::new();
+        if let Some(Node::Map(apps_node)) = entry.get("apps") {
+            for (app_id, size_node) in apps_node {
+                if let Node::String(size) = size_node
+                    && let Ok(size) = size.parse()
+                {
+                    apps.insert(app_id.clone(), size);
+                }
+            }
+        }
+        libraries.push(Library {
+            path: PathBuf::from(path),
+            apps,
+        });
+    }
+    Ok(libraries)
+}
+
+/// Finds the library that has `app_id` installed, if any.
+pub fn find_library_for_app<'a>(libraries: &'a [Library], app_id: &str) -> Option<&'a Library> {
+    libraries.iter().find(|library| library.apps.contains_key(app_id))
+}
+
+/// Parses a text-VDF document into a `Dictionary`.
+pub fn parse(input: &str) -> Result<Dictionary> {
+    let mut parser = Parser {
+        chars: input.chars().peekable(),
+    };
+    parser.parse_dict()
+}
+
+struct Parser<'a> {
+    chars: Peekable<Chars<'a>>,
+}
+
+impl Parser<'_> {
+    fn skip_ws(&mut self) {
+        while matches!(self.chars.peek(), Some(c) if c.is_whitespace()) {
+            self.chars.next();
+        }
+    }
+
+    fn parse_dict(&mut self) -> Result<Dictionary> {
+        let mut map = HashMap::new();
+        loop {
+            self.skip_ws();
+            match self.chars.peek() {
+                None => break,
+                Some('}') => {
+                    self.chars.next();
+                    break;
+                }
+                Some('"') => {
+                    let key = self.read_quoted()?;
+                    self.skip_ws();
+                    match self.chars.peek() {
+                        Some('"') => {
+                            let value = self.read_quoted()?;
+                            map.insert(key, Node::String(value));
+                        }
+                        Some('{') => {
+                            self.chars.next();
+                            let child = self.parse_dict()?;
+                            map.insert(key, Node::Map(child));
+                        }
+                        other => {
+                            return Err(anyhow!("expected value after key {key:?}, found {other:?}"));
+                        }
+                    }
+                }
+                other => return Err(anyhow!("unexpected token in vdf document: {other:?}")),
+            }
+        }
+        Ok(map)
+    }
+
+    fn read_quoted(&mut self) -> Result<String> {
+        self.skip_ws();
+        if self.chars.next() != Some('"') {
+            return Err(anyhow!("expected opening quote"));
+        }
+        let mut value = String::new();
+        loop {
+            match self.chars.next() {
+                Some('"') => break,
+                Some('\\') => match self.chars.next() {
+                    Some(c) => value.push(c),
+                    None => return Err(anyhow!("unterminated escape in quoted string")),
+                },
+                Some(c) => value.push(c),
+                None => return Err(anyhow!("unterminated quoted string")),
+            }
+        }
+        Ok(value)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const SAMPLE: &str = r#"
+"libraryfolders"
+{
+    "0"
+    {
+        "path"        "/home/user/.local/share/Steam"
+        "label"       ""
+        "apps"
+        {
+            "1001"        "123456789"
+        }
+    }
+    "1"
+    {
+        "path"        "/mnt/data/SteamLibrary"
+        "apps"
+        {
+            "2002"        "987654321"
+        }
+    }
+}
+"#;
+
+    #[test]
+    fn parse_reads_nested_maps_and_strings() {
+        let dict = parse(SAMPLE).unwrap();
+        let Some(Node::Map(libraries)) = dict.get("libraryfolders") else {
+            panic!("expected a libraryfolders map");
+        };
+        let Some(Node::Map(first)) = libraries.get("0") else {
+            panic!("expected library \"0\"");
+        };
+        assert!(matches!(
+            first.get("path"),
+            Some(Node::String(path)) if path == "/home/user/.local/share/Steam"
+        ));
+    }
+
+    #[test]
+    fn discover_libraries_descends_past_the_wrapper_key() {
+        smol::block_on(async {
+            let dir = std::env::temp_dir().join("steam-pml-run-test-vdf-discover");
+            let steamapps = dir.join(STEAM_APPS);
+            fs::create_dir_all(&steamapps).await.unwrap();
+            fs::write(steamapps.join("libraryfolders.vdf"), SAMPLE).await.unwrap();
+
+            let libraries = discover_libraries(&dir).await.unwrap();
+            assert_eq!(libraries.len(), 2);
+
+            let found = find_library_for_app(&libraries, "2002").expect("app present in library 1");
+            assert_eq!(found.path, PathBuf::from("/mnt/data/SteamLibrary"));
+            assert_eq!(found.apps.get("2002"), Some(&987654321));
+            assert!(find_library_for_app(&libraries, "9999").is_none());
+
+            fs::remove_dir_all(&dir).await.unwrap();
+        });
+    }
+}